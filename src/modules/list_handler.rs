@@ -0,0 +1,48 @@
+use super::mapper;
+use super::use_handler::get_downloads_folder;
+use anyhow::Result;
+use std::cmp::Ordering;
+
+pub async fn start() -> Result<()> {
+    let downloads_dir = get_downloads_folder().await?;
+    let active_version = mapper::get_active_version().await?;
+
+    let mut entries = tokio::fs::read_dir(&downloads_dir).await?;
+    let mut versions = Vec::new();
+
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            if let Some(name) = entry.file_name().to_str() {
+                versions.push(String::from(name));
+            }
+        }
+    }
+
+    if versions.is_empty() {
+        println!("No versions installed yet");
+        return Ok(());
+    }
+
+    // Newest first, the way users expect a version manager's list to read.
+    versions.sort_by(|a, b| match (parse_semver(a), parse_semver(b)) {
+        (Some(a), Some(b)) => b.cmp(&a),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => b.cmp(a),
+    });
+
+    for version in versions {
+        let marker = if active_version.as_deref() == Some(version.as_str()) {
+            "*"
+        } else {
+            " "
+        };
+        println!("{marker} {version}");
+    }
+
+    Ok(())
+}
+
+fn parse_semver(version: &str) -> Option<semver::Version> {
+    semver::Version::parse(version.trim_start_matches('v')).ok()
+}
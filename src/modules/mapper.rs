@@ -0,0 +1,189 @@
+use anyhow::{anyhow, Result};
+use dirs::data_local_dir;
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
+
+pub const BIN_DIR: &str = "nvim-bin";
+
+pub async fn switch_version(version: &str, installation_dir: &Path) -> Result<()> {
+    let nvim_binary = locate_nvim_binary(installation_dir)?;
+    let link_path = get_link_path(&get_bin_dir().await?);
+    let tmp_path = link_path.with_extension("tmp");
+
+    // Write the new link/shim next to the old one, then rename it over —
+    // rename is atomic on Unix and Windows, so nvim-bin is never left empty.
+    create_link(&nvim_binary, &tmp_path)?;
+    tokio::fs::rename(&tmp_path, &link_path).await?;
+
+    println!("Switched to version {version}");
+
+    Ok(())
+}
+
+pub async fn get_active_version() -> Result<Option<String>> {
+    let link_path = get_link_path(&get_bin_dir().await?);
+
+    let target = match read_link_target(&link_path).await? {
+        Some(target) => target,
+        None => return Ok(None),
+    };
+
+    Ok(version_from_nvim_path(&target))
+}
+
+fn version_from_nvim_path(target: &Path) -> Option<String> {
+    target
+        .ancestors()
+        .nth(3)
+        .and_then(|path| path.file_name())
+        .and_then(|name| name.to_str())
+        .map(String::from)
+}
+
+#[cfg(unix)]
+async fn read_link_target(link_path: &Path) -> Result<Option<PathBuf>> {
+    match tokio::fs::read_link(link_path).await {
+        Ok(target) => Ok(Some(target)),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(windows)]
+async fn read_link_target(link_path: &Path) -> Result<Option<PathBuf>> {
+    let content = match tokio::fs::read_to_string(link_path).await {
+        Ok(content) => content,
+        Err(_) => return Ok(None),
+    };
+
+    Ok(parse_shim_target(&content))
+}
+
+// The shim is "@echo off\r\n\"<target>\" %*\r\n" — pull the quoted path back out.
+#[cfg(windows)]
+fn parse_shim_target(content: &str) -> Option<PathBuf> {
+    content.lines().find_map(|line| {
+        let start = line.find('"')?;
+        let rest = &line[start + 1..];
+        let end = rest.find('"')?;
+        Some(PathBuf::from(&rest[..end]))
+    })
+}
+
+pub async fn get_bin_dir() -> Result<PathBuf> {
+    let data_dir = match data_local_dir() {
+        None => return Err(anyhow!("Couldn't get data folder")),
+        Some(value) => value,
+    };
+    let bin_dir = data_dir.join(BIN_DIR);
+
+    if tokio::fs::metadata(&bin_dir).await.is_err() {
+        if let Err(error) = tokio::fs::create_dir_all(&bin_dir).await {
+            return Err(anyhow!(error));
+        }
+    }
+
+    Ok(bin_dir)
+}
+
+fn get_link_path(bin_dir: &Path) -> PathBuf {
+    if cfg!(target_os = "windows") {
+        bin_dir.join("nvim.cmd")
+    } else {
+        bin_dir.join("nvim")
+    }
+}
+
+fn locate_nvim_binary(installation_dir: &Path) -> Result<PathBuf> {
+    let platform_dir = if cfg!(target_os = "windows") {
+        "nvim-win64"
+    } else if cfg!(target_os = "macos") {
+        "nvim-macos"
+    } else {
+        "nvim-linux64"
+    };
+    let binary_name = get_binary_name();
+    let binary_path = installation_dir
+        .join(platform_dir)
+        .join("bin")
+        .join(binary_name);
+
+    if !binary_path.exists() {
+        return Err(anyhow!(
+            "Couldn't find the nvim binary in {}",
+            installation_dir.display()
+        ));
+    }
+
+    Ok(binary_path)
+}
+
+fn get_binary_name() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "nvim.exe"
+    } else {
+        "nvim"
+    }
+}
+
+#[cfg(unix)]
+fn create_link(target: &Path, link: &Path) -> Result<()> {
+    symlink(target, link).map_err(|error| anyhow!("Failed to link {}: {error}", link.display()))
+}
+
+#[cfg(windows)]
+fn create_link(target: &Path, link: &Path) -> Result<()> {
+    // Unprivileged symlinks aren't reliably available on Windows, so drop in
+    // a small proxy shim that forwards to the real binary instead.
+    std::fs::write(
+        link,
+        format!("@echo off\r\n\"{}\" %*\r\n", target.display()),
+    )
+    .map_err(|error| anyhow!("Failed to write shim {}: {error}", link.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_from_nvim_path_reads_the_version_directory() {
+        let target = PathBuf::from("/home/user/.local/share/bob/v0.9.0/nvim-linux64/bin/nvim");
+        assert_eq!(
+            version_from_nvim_path(&target),
+            Some(String::from("v0.9.0"))
+        );
+    }
+
+    #[test]
+    fn version_from_nvim_path_handles_nightly() {
+        let target = PathBuf::from("/home/user/.local/share/bob/nightly/nvim-linux64/bin/nvim");
+        assert_eq!(
+            version_from_nvim_path(&target),
+            Some(String::from("nightly"))
+        );
+    }
+
+    #[test]
+    fn version_from_nvim_path_rejects_shallow_paths() {
+        let target = PathBuf::from("nvim");
+        assert_eq!(version_from_nvim_path(&target), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn parse_shim_target_extracts_the_quoted_path() {
+        let content = "@echo off\r\n\"C:\\bob\\v0.9.0\\nvim-win64\\bin\\nvim.exe\" %*\r\n";
+        assert_eq!(
+            parse_shim_target(content),
+            Some(PathBuf::from("C:\\bob\\v0.9.0\\nvim-win64\\bin\\nvim.exe"))
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn parse_shim_target_rejects_unrelated_content() {
+        assert_eq!(parse_shim_target("not a shim"), None);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod list_handler;
+pub mod mapper;
+pub mod use_handler;
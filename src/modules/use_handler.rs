@@ -1,15 +1,21 @@
+use super::mapper;
 use crate::models::{StableVersion, DownloadedFile};
 use anyhow::{anyhow, Result};
 use clap::ArgMatches;
 use dirs::data_local_dir;
+use flate2::read::GzDecoder;
 use futures_util::stream::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
 use regex::Regex;
 use reqwest::Client;
+use semver::VersionReq;
+use sha2::{Digest, Sha256};
 use std::cmp::min;
-use std::path::PathBuf;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tar::Archive;
 use tokio::io::AsyncWriteExt;
-use tokio::process::Command;
+use zip::ZipArchive;
 
 pub async fn start(command: &ArgMatches) -> Result<()> {
     let client = Client::new();
@@ -22,18 +28,120 @@ pub async fn start(command: &ArgMatches) -> Result<()> {
         return Err(anyhow!("Todo.."));
     };
 
-    let downloaded_version = match download_version(&client, &version).await {
-        Ok(value) => value,
-        Err(error) => return Err(anyhow!(error)),
+    let downloads_dir = get_downloads_folder().await?;
+    let installation_dir = downloads_dir.join(&version);
+    let is_installed = tokio::fs::metadata(&installation_dir).await.is_ok();
+
+    // Nightly always maps to the same tag, so a matching build id (rather
+    // than just "is it installed") is what tells us it's still current.
+    let remote_build_id = if version == "nightly" {
+        Some(fetch_nightly_build_id(&client).await?)
+    } else {
+        None
+    };
+
+    let local_build_id = if remote_build_id.is_some() {
+        read_nightly_metadata(&downloads_dir).await
+    } else {
+        None
     };
+    let up_to_date =
+        is_installed && is_nightly_current(remote_build_id.as_deref(), local_build_id.as_deref());
+
+    if up_to_date {
+        if remote_build_id.is_some() {
+            println!("Nightly is already up to date");
+        }
+    } else {
+        let downloaded_version = match download_version(&client, &version).await {
+            Ok(value) => value,
+            Err(error) => return Err(anyhow!(error)),
+        };
 
-    if let Err(error) = install_version(downloaded_version).await {
-        return Err(anyhow!(error));
+        if let Err(error) = install_version(downloaded_version).await {
+            return Err(anyhow!(error));
+        }
+
+        if let Some(build_id) = &remote_build_id {
+            write_nightly_metadata(&downloads_dir, build_id).await?;
+        }
     }
 
+    mapper::switch_version(&version, &installation_dir).await?;
+
     Ok(())
 }
 
+async fn fetch_nightly_build_id(client: &Client) -> Result<String> {
+    let response = client
+        .get("https://api.github.com/repos/neovim/neovim/releases/tags/nightly")
+        .header("user-agent", "bob")
+        .header("Accept", "application/vnd.github.v3+json")
+        .send()
+        .await?
+        .text()
+        .await?;
+
+    let release: GithubRelease = serde_json::from_str(response.as_str())?;
+    let asset_name = get_asset_name();
+
+    // `target_commitish` on the nightly release is just the branch it was
+    // cut from (e.g. "master") and never changes between builds, so it can't
+    // tell two nightlies apart. Each build re-uploads its assets though, so
+    // the asset's own `updated_at` does change every time nightly moves.
+    release
+        .assets
+        .into_iter()
+        .find(|asset| asset.name == asset_name)
+        .map(|asset| asset.updated_at)
+        .ok_or_else(|| anyhow!("Nightly release doesn't publish a {asset_name} asset"))
+}
+
+fn is_nightly_current(remote_build_id: Option<&str>, local_build_id: Option<&str>) -> bool {
+    match remote_build_id {
+        Some(remote_id) => local_build_id == Some(remote_id),
+        None => true,
+    }
+}
+
+fn nightly_metadata_path(downloads_dir: &Path) -> PathBuf {
+    downloads_dir.join("nightly.json")
+}
+
+async fn read_nightly_metadata(downloads_dir: &Path) -> Option<String> {
+    let content = tokio::fs::read_to_string(nightly_metadata_path(downloads_dir))
+        .await
+        .ok()?;
+    let metadata: NightlyMetadata = serde_json::from_str(&content).ok()?;
+    Some(metadata.build_id)
+}
+
+async fn write_nightly_metadata(downloads_dir: &Path, build_id: &str) -> Result<()> {
+    let metadata = NightlyMetadata {
+        build_id: String::from(build_id),
+    };
+    let content = serde_json::to_string(&metadata)?;
+    tokio::fs::write(nightly_metadata_path(downloads_dir), content).await?;
+
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+struct GithubRelease {
+    assets: Vec<GithubAsset>,
+}
+
+#[derive(serde::Deserialize)]
+struct GithubAsset {
+    name: String,
+    updated_at: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct NightlyMetadata {
+    build_id: String,
+}
+
 async fn parse_version(client: &Client, version: &str) -> Result<String> {
     match version {
         "nightly" => Ok(String::from(version)),
@@ -60,9 +168,62 @@ async fn parse_version(client: &Client, version: &str) -> Result<String> {
                 }
                 return Ok(returned_version);
             }
-            Err(anyhow!("Please provide a proper version string"))
+            resolve_version_range(client, version).await
+        }
+    }
+}
+
+async fn resolve_version_range(client: &Client, range: &str) -> Result<String> {
+    let request = VersionReq::parse(range.trim_start_matches('v'))
+        .map_err(|_| anyhow!("Please provide a proper version string"))?;
+
+    let tags = fetch_tags(client).await?;
+
+    select_best_tag(&request, tags).ok_or_else(|| anyhow!("No Neovim release satisfies {range}"))
+}
+
+fn select_best_tag(request: &VersionReq, tags: Vec<String>) -> Option<String> {
+    tags.into_iter()
+        .filter_map(|tag| {
+            let parsed = semver::Version::parse(tag.trim_start_matches('v')).ok()?;
+            Some((parsed, tag))
+        })
+        .filter(|(parsed, _)| request.matches(parsed))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, tag)| tag)
+}
+
+async fn fetch_tags(client: &Client) -> Result<Vec<String>> {
+    let mut tags = Vec::new();
+    let mut page: u32 = 1;
+
+    loop {
+        let response = client
+            .get("https://api.github.com/repos/neovim/neovim/tags")
+            .query(&[("per_page", "100"), ("page", &page.to_string())])
+            .header("user-agent", "bob")
+            .header("Accept", "application/vnd.github.v3+json")
+            .send()
+            .await?
+            .text()
+            .await?;
+
+        let page_tags: Vec<GithubTag> = serde_json::from_str(response.as_str())?;
+
+        if page_tags.is_empty() {
+            break;
         }
+
+        tags.extend(page_tags.into_iter().map(|tag| tag.name));
+        page += 1;
     }
+
+    Ok(tags)
+}
+
+#[derive(serde::Deserialize)]
+struct GithubTag {
+    name: String,
 }
 
 async fn download_version(client: &Client, version: &String) -> Result<DownloadedFile> {
@@ -87,15 +248,16 @@ async fn download_version(client: &Client, version: &String) -> Result<Downloade
                 };
                 let downloads_dir_str = downloads_dir.to_str().unwrap();
                 let file_type = get_file_type();
-                let mut file =
-                    tokio::fs::File::create(format!("{downloads_dir_str}/{version}.{file_type}"))
-                        .await?;
+                let file_path = format!("{downloads_dir_str}/{version}.{file_type}");
+                let mut file = tokio::fs::File::create(&file_path).await?;
 
                 let mut downloaded: u64 = 0;
+                let mut hasher = Sha256::new();
 
                 while let Some(item) = response_bytes.next().await {
                     let chunk = item.or(anyhow::private::Err(anyhow::Error::msg("hello")))?;
-                    file.write(&chunk).await;
+                    hasher.update(&chunk);
+                    file.write_all(&chunk).await?;
                     let new = min(downloaded + (chunk.len() as u64), total_size);
                     downloaded = new;
                     pb.set_position(new);
@@ -105,6 +267,22 @@ async fn download_version(client: &Client, version: &String) -> Result<Downloade
                     "Downloaded version {version} to {downloads_dir_str}/{version}.{file_type}"
                 ));
 
+                let expected_checksum = match fetch_checksum(client, version).await {
+                    Ok(value) => value,
+                    Err(error) => {
+                        tokio::fs::remove_file(&file_path).await.ok();
+                        return Err(error);
+                    }
+                };
+                let actual_checksum = format!("{:x}", hasher.finalize());
+
+                if actual_checksum != expected_checksum {
+                    tokio::fs::remove_file(&file_path).await.ok();
+                    return Err(anyhow!(
+                        "Checksum mismatch for version {version}: expected {expected_checksum}, got {actual_checksum}"
+                    ));
+                }
+
                 Ok(DownloadedFile {
                     path: downloads_dir,
                     extension: file_type,
@@ -118,20 +296,37 @@ async fn download_version(client: &Client, version: &String) -> Result<Downloade
     }
 }
 
+async fn fetch_checksum(client: &Client, version: &str) -> Result<String> {
+    let checksum_url = format!(
+        "https://github.com/neovim/neovim/releases/download/{version}/{}.sha256sum",
+        get_asset_name()
+    );
+
+    let response = client
+        .get(checksum_url)
+        .header("user-agent", "bob")
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Couldn't fetch checksum for version {version}"));
+    }
+
+    let body = response.text().await?;
+
+    body.split_whitespace()
+        .next()
+        .map(String::from)
+        .ok_or_else(|| anyhow!("Malformed checksum file for version {version}"))
+}
+
 async fn send_request(
     client: &Client,
     version: &String,
 ) -> Result<reqwest::Response, reqwest::Error> {
-    let os = if cfg!(target_os = "linux") {
-        "linux64"
-    } else if cfg!(target_os = "windows") {
-        "win64"
-    } else {
-        "macos"
-    };
     let request_url = format!(
-        "https://github.com/neovim/neovim/releases/download/{version}/nvim-{os}.{}",
-        get_file_type()
+        "https://github.com/neovim/neovim/releases/download/{version}/{}",
+        get_asset_name()
     );
 
     client
@@ -141,6 +336,17 @@ async fn send_request(
         .await
 }
 
+fn get_asset_name() -> String {
+    let os = if cfg!(target_os = "linux") {
+        "linux64"
+    } else if cfg!(target_os = "windows") {
+        "win64"
+    } else {
+        "macos"
+    };
+    format!("nvim-{os}.{}", get_file_type())
+}
+
 fn get_file_type() -> String {
     if cfg!(target_family = "windows") {
         String::from("zip")
@@ -149,7 +355,7 @@ fn get_file_type() -> String {
     }
 }
 
-async fn get_downloads_folder() -> Result<PathBuf> {
+pub(crate) async fn get_downloads_folder() -> Result<PathBuf> {
     let data_dir = match data_local_dir() {
         None => return Err(anyhow!("Couldn't get data folder")),
         Some(value) => value,
@@ -167,38 +373,136 @@ async fn get_downloads_folder() -> Result<PathBuf> {
 
 async fn install_version(downloaded_file: DownloadedFile) -> Result<()> {
     println!("Installing");
-    let output = if cfg!(target_os = "windows") {
-        Command::new("powershell")
-            .current_dir(downloaded_file.path)
-            .arg("-c")
-            .arg(format!(
-                "\
-        Add-Type -AssemblyName System.IO.Compression.FileSystem
-        [System.IO.Compression.ZipFile]::ExtractToDirectory(\"{}.{}\", \"./{0}\")
-        ", downloaded_file.name, downloaded_file.extension))
-            .output()
-            .await?
-    } else {
-        Command::new("bash")
-            .current_dir(downloaded_file.path)
-            .arg("-c")
-            .arg(format!(
-                "\
-            tar -xf {}
-            ",
-                downloaded_file.name
-            ))
-            .output()
-            .await?
-    };
-    if !output.status.success() {
-        return Err(anyhow!(
-            "Failed to uncompress {} {}",
-            downloaded_file.name,
-            String::from_utf8_lossy(&output.stderr)
-        ));
+
+    let archive_path = downloaded_file
+        .path
+        .join(format!("{}.{}", downloaded_file.name, downloaded_file.extension));
+    let destination = downloaded_file.path.join(&downloaded_file.name);
+    let extension = downloaded_file.extension.clone();
+
+    tokio::task::spawn_blocking(move || {
+        if extension == "zip" {
+            extract_zip(&archive_path, &destination)
+        } else {
+            extract_tar(&archive_path, &destination)
+        }
+    })
+    .await??;
+
+    println!("Finished installing");
+
+    Ok(())
+}
+
+fn extract_tar(archive_path: &Path, destination: &Path) -> Result<()> {
+    let file = File::open(archive_path)
+        .map_err(|error| anyhow!("Failed to open {}: {error}", archive_path.display()))?;
+    let mut archive = Archive::new(GzDecoder::new(file));
+    archive
+        .unpack(destination)
+        .map_err(|error| anyhow!("Failed to extract {}: {error}", archive_path.display()))
+}
+
+fn extract_zip(archive_path: &Path, destination: &Path) -> Result<()> {
+    let file = File::open(archive_path)
+        .map_err(|error| anyhow!("Failed to open {}: {error}", archive_path.display()))?;
+    let mut archive = ZipArchive::new(file)
+        .map_err(|error| anyhow!("Failed to read {}: {error}", archive_path.display()))?;
+
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|error| anyhow!("Failed to read entry {index} in {}: {error}", archive_path.display()))?;
+        let entry_path = destination.join(entry.mangled_name());
+
+        if entry.is_dir() {
+            std::fs::create_dir_all(&entry_path)?;
+            continue;
+        }
+
+        if let Some(parent) = entry_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut outfile = File::create(&entry_path)
+            .map_err(|error| anyhow!("Failed to create {}: {error}", entry_path.display()))?;
+        std::io::copy(&mut entry, &mut outfile)
+            .map_err(|error| anyhow!("Failed to write {}: {error}", entry_path.display()))?;
+
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&entry_path, std::fs::Permissions::from_mode(mode))?;
+        }
     }
-    println!("Finsihed installing");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| String::from(*name)).collect()
+    }
+
+    #[test]
+    fn select_best_tag_picks_the_highest_match() {
+        let request = VersionReq::parse("0.8").unwrap();
+        let result = select_best_tag(&request, tags(&["v0.7.2", "v0.8.0", "v0.8.3", "v0.9.0"]));
+
+        assert_eq!(result, Some(String::from("v0.8.3")));
+    }
+
+    #[test]
+    fn select_best_tag_honors_comparison_ranges() {
+        let request = VersionReq::parse(">=0.7, <0.9").unwrap();
+        let result = select_best_tag(&request, tags(&["v0.6.0", "v0.7.0", "v0.8.5", "v0.9.0"]));
+
+        assert_eq!(result, Some(String::from("v0.8.5")));
+    }
+
+    #[test]
+    fn select_best_tag_ignores_unparsable_tags() {
+        let request = VersionReq::parse("0.9").unwrap();
+        let result = select_best_tag(&request, tags(&["nightly", "stable", "v0.9.1"]));
+
+        assert_eq!(result, Some(String::from("v0.9.1")));
+    }
+
+    #[test]
+    fn select_best_tag_returns_none_when_nothing_matches() {
+        let request = VersionReq::parse("2.0").unwrap();
+        let result = select_best_tag(&request, tags(&["v0.7.0", "v0.9.0"]));
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn is_nightly_current_ignores_non_nightly_versions() {
+        assert!(is_nightly_current(None, None));
+        assert!(is_nightly_current(None, Some("2024-01-01T00:00:00Z")));
+    }
+
+    #[test]
+    fn is_nightly_current_matches_on_build_id() {
+        assert!(is_nightly_current(
+            Some("2024-01-01T00:00:00Z"),
+            Some("2024-01-01T00:00:00Z")
+        ));
+    }
+
+    #[test]
+    fn is_nightly_current_detects_a_moved_build() {
+        assert!(!is_nightly_current(
+            Some("2024-02-01T00:00:00Z"),
+            Some("2024-01-01T00:00:00Z")
+        ));
+    }
+
+    #[test]
+    fn is_nightly_current_treats_no_local_record_as_stale() {
+        assert!(!is_nightly_current(Some("2024-01-01T00:00:00Z"), None));
+    }
+}